@@ -1,31 +1,172 @@
+use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use rustfft::{num_complex::Complex32, num_traits::ToPrimitive, FftPlanner};
 use eframe::{self, egui, App};
 use egui_plot::{self, Plot, Line};
+use ringbuf::{HeapRb, Rb};
 
-const FFT_SIZE: usize = 1024;
+const DEFAULT_FFT_SIZE: usize = 1024;
+/// FFT lengths offered in the UI, trading frequency resolution against latency.
+const FFT_SIZE_OPTIONS: [usize; 6] = [256, 512, 1024, 2048, 4096, 8192];
+/// Number of past spectra kept for the waterfall view.
+const SPECTROGRAM_HISTORY: usize = 200;
+
+/// Analysis window applied to each frame before the FFT to reduce spectral leakage.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum WindowKind {
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+}
+
+impl WindowKind {
+    /// Coefficient for sample `n` of an `len`-sample window.
+    fn coefficient(self, n: usize, len: usize) -> f32 {
+        let n = n as f32;
+        let len = len as f32;
+        match self {
+            WindowKind::Rectangular => 1.0,
+            WindowKind::Hann => 0.5 * (1.0 - (2.0 * std::f32::consts::PI * n / (len - 1.0)).cos()),
+            WindowKind::Hamming => {
+                0.54 - 0.46 * (2.0 * std::f32::consts::PI * n / (len - 1.0)).cos()
+            }
+            WindowKind::Blackman => {
+                0.42 - 0.5 * (2.0 * std::f32::consts::PI * n / (len - 1.0)).cos()
+                    + 0.08 * (4.0 * std::f32::consts::PI * n / (len - 1.0)).cos()
+            }
+        }
+    }
+
+    /// Precomputes the coefficient table and its coherent gain (mean coefficient),
+    /// used to keep magnitudes calibrated after windowing.
+    fn table(self, len: usize) -> (Vec<f32>, f32) {
+        let coeffs: Vec<f32> = (0..len).map(|n| self.coefficient(n, len)).collect();
+        let gain = coeffs.iter().sum::<f32>() / len as f32;
+        (coeffs, gain)
+    }
+}
+
+/// How much successive FFT frames overlap, controlling the hop size between transforms.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum OverlapKind {
+    Half,
+    ThreeQuarter,
+}
+
+impl OverlapKind {
+    /// Samples to advance between transforms for a given FFT length.
+    fn hop_size(self, fft_len: usize) -> usize {
+        match self {
+            OverlapKind::Half => fft_len / 2,
+            OverlapKind::ThreeQuarter => fft_len / 4,
+        }
+    }
+}
 
 fn main() -> eframe::Result<()> {
     let shared_data = Arc::new(Mutex::new(AudioData::new()));
-    start_audio_stream(shared_data.clone());
+    let host = cpal::default_host();
+    let devices = input_device_names(&host);
+    let selected_device = host
+        .default_input_device()
+        .and_then(|d| d.name().ok())
+        .unwrap_or_default();
+
+    let (stream, device_error) = select_device(&host, &selected_device, shared_data.clone());
 
     let options = eframe::NativeOptions::default();
     eframe::run_native(
         "Rust Audio Analyzer",
         options,
-        Box::new(|_cc| Ok(Box::new(AudioApp { shared_data }))),
+        Box::new(|_cc| {
+            Ok(Box::new(AudioApp {
+                shared_data,
+                host,
+                devices,
+                selected_device,
+                stream,
+                device_error,
+                show_waterfall: false,
+                spectrogram_texture: None,
+                spectrogram_bins: 0,
+                spectrogram_rendered_version: 0,
+                spectrogram_write_col: 0,
+            }))
+        }),
     )
 }
 
 struct AudioApp {
     shared_data: Arc<Mutex<AudioData>>,
+    host: cpal::Host,
+    devices: Vec<String>,
+    selected_device: String,
+    stream: Option<cpal::Stream>,
+    device_error: Option<String>,
+    show_waterfall: bool,
+    spectrogram_texture: Option<egui::TextureHandle>,
+    spectrogram_bins: usize,
+    spectrogram_rendered_version: u64,
+    spectrogram_write_col: usize,
 }
 
 impl App for AudioApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         egui::CentralPanel::default().show(ctx, |ui| {
-            let data = self.shared_data.lock().unwrap();
+            egui::ComboBox::from_label("Input device")
+                .selected_text(&self.selected_device)
+                .show_ui(ui, |ui| {
+                    for name in self.devices.clone() {
+                        if ui
+                            .selectable_value(&mut self.selected_device, name.clone(), &name)
+                            .clicked()
+                        {
+                            // Drop the old stream first so the device is released
+                            // before the new one claims it.
+                            drop(self.stream.take());
+                            let (stream, error) =
+                                select_device(&self.host, &name, self.shared_data.clone());
+                            self.stream = stream;
+                            self.device_error = error;
+                        }
+                    }
+                });
+            if let Some(error) = &self.device_error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+
+            let mut data = self.shared_data.lock().unwrap();
+
+            egui::ComboBox::from_label("Window")
+                .selected_text(format!("{:?}", data.window))
+                .show_ui(ui, |ui| {
+                    for kind in [
+                        WindowKind::Rectangular,
+                        WindowKind::Hann,
+                        WindowKind::Hamming,
+                        WindowKind::Blackman,
+                    ] {
+                        ui.selectable_value(&mut data.window, kind, format!("{:?}", kind));
+                    }
+                });
+
+            egui::ComboBox::from_label("FFT Size")
+                .selected_text(data.fft_len.to_string())
+                .show_ui(ui, |ui| {
+                    for len in FFT_SIZE_OPTIONS {
+                        ui.selectable_value(&mut data.fft_len, len, len.to_string());
+                    }
+                });
+
+            egui::ComboBox::from_label("Overlap")
+                .selected_text(format!("{:?}", data.overlap))
+                .show_ui(ui, |ui| {
+                    for kind in [OverlapKind::Half, OverlapKind::ThreeQuarter] {
+                        ui.selectable_value(&mut data.overlap, kind, format!("{:?}", kind));
+                    }
+                });
 
             ui.heading("🎧 Waveform");
             Plot::new("waveform")
@@ -41,94 +182,358 @@ impl App for AudioApp {
 
             ui.separator();
             ui.heading("📊 FFT Spectrum");
+            ui.label(format!(
+                "Peak: {:.1} Hz ({:.1} dBFS)",
+                data.peak_frequency, data.peak_magnitude_db
+            ));
             Plot::new("fft")
                 .height(150.0)
+                .x_axis_label("Frequency (Hz)")
+                .y_axis_label("Magnitude (dBFS)")
                 .show(ui, |plot_ui| {
                     let points: Vec<_> = data.spectrum
                         .iter()
                         .enumerate()
-                        .map(|(i, &v)| [i as f64, v as f64])
+                        .map(|(i, &v)| [data.bin_frequency(i), v as f64])
                         .collect();
                     plot_ui.line(Line::new("points", points));
+
+                    let marker = vec![
+                        [data.peak_frequency, NOISE_FLOOR_DB as f64],
+                        [data.peak_frequency, data.peak_magnitude_db as f64],
+                    ];
+                    plot_ui.line(Line::new("peak", marker).color(egui::Color32::RED));
                 });
+
+            ui.separator();
+            ui.checkbox(&mut self.show_waterfall, "🌊 Show waterfall");
+            if self.show_waterfall {
+                ui.heading("🌊 Spectrogram");
+
+                let bins = data.fft_len / 2;
+                if self.spectrogram_texture.is_none() || self.spectrogram_bins != bins {
+                    let blank =
+                        egui::ColorImage::new([SPECTROGRAM_HISTORY, bins], egui::Color32::BLACK);
+                    self.spectrogram_texture = Some(ui.ctx().load_texture(
+                        "spectrogram",
+                        blank,
+                        egui::TextureOptions::NEAREST_REPEAT,
+                    ));
+                    self.spectrogram_bins = bins;
+                    self.spectrogram_rendered_version = 0;
+                    self.spectrogram_write_col = 0;
+                }
+                let texture = self.spectrogram_texture.as_mut().unwrap();
+
+                // The audio thread zeroes data.spectrogram_version whenever it
+                // rebuilds its ring for a new FFT length or overlap (including a
+                // freshly started stream picking up non-default settings). An
+                // overlap change leaves the bin count as it is, so the UI can't
+                // rely on `bins` alone to notice. Catch the rewind here and force
+                // a full repaint.
+                if data.spectrogram_version < self.spectrogram_rendered_version {
+                    texture.set(
+                        egui::ColorImage::new([SPECTROGRAM_HISTORY, bins], egui::Color32::BLACK),
+                        egui::TextureOptions::NEAREST_REPEAT,
+                    );
+                    self.spectrogram_rendered_version = 0;
+                    self.spectrogram_write_col = 0;
+                }
+
+                // Only the frames pushed since the last paint are new; write just
+                // those columns into the texture instead of re-uploading the image.
+                let new_frames = (data.spectrogram_version - self.spectrogram_rendered_version)
+                    .min(SPECTROGRAM_HISTORY as u64) as usize;
+                for spectrum in data.spectrogram.iter().rev().take(new_frames).rev() {
+                    let column = spectrogram_column(spectrum, bins);
+                    texture.set_partial(
+                        [self.spectrogram_write_col, 0],
+                        column,
+                        egui::TextureOptions::NEAREST_REPEAT,
+                    );
+                    self.spectrogram_write_col = (self.spectrogram_write_col + 1) % SPECTROGRAM_HISTORY;
+                }
+                self.spectrogram_rendered_version = data.spectrogram_version;
+
+                // The texture wraps horizontally, so scrolling is just sliding the
+                // sampled UV window past the column we're about to overwrite next.
+                let scroll = self.spectrogram_write_col as f32 / SPECTROGRAM_HISTORY as f32;
+                let uv = egui::Rect::from_min_max(
+                    egui::pos2(scroll, 0.0),
+                    egui::pos2(scroll + 1.0, 1.0),
+                );
+                ui.add(egui::Image::new(&*texture).uv(uv));
+            }
         });
 
         ctx.request_repaint(); // atualização contínua
     }
 }
 
+const NOISE_FLOOR_DB: f32 = -120.0;
+
 struct AudioData {
     waveform: Vec<f32>,
     spectrum: Vec<f32>,
+    window: WindowKind,
+    sample_rate: u32,
+    fft_len: usize,
+    overlap: OverlapKind,
+    peak_frequency: f64,
+    peak_magnitude_db: f32,
+    spectrogram: VecDeque<Vec<f32>>,
+    /// Bumped each time a frame is pushed to `spectrogram`, so the UI can tell
+    /// how many new columns to paint without rescanning the whole history.
+    spectrogram_version: u64,
 }
 
 impl AudioData {
     fn new() -> Self {
         Self {
-            waveform: vec![0.0; FFT_SIZE],
-            spectrum: vec![0.0; FFT_SIZE / 2],
+            waveform: vec![0.0; DEFAULT_FFT_SIZE],
+            spectrum: vec![0.0; DEFAULT_FFT_SIZE / 2],
+            window: WindowKind::Hann,
+            sample_rate: 44_100,
+            fft_len: DEFAULT_FFT_SIZE,
+            overlap: OverlapKind::Half,
+            peak_frequency: 0.0,
+            peak_magnitude_db: NOISE_FLOOR_DB,
+            spectrogram: VecDeque::with_capacity(SPECTROGRAM_HISTORY),
+            spectrogram_version: 0,
         }
     }
+
+    /// Frequency in Hz represented by spectrum bin `i`.
+    fn bin_frequency(&self, i: usize) -> f64 {
+        i as f64 * self.sample_rate as f64 / self.fft_len as f64
+    }
 }
 
-fn start_audio_stream(shared_data: Arc<Mutex<AudioData>>) {
-    let host = cpal::default_host();
-    let device = host.default_input_device().expect("No input device");
-    let config = device.default_input_config().unwrap();
+/// Finds the dominant bin (ignoring DC) and refines it with parabolic
+/// interpolation over the log-magnitudes of its neighbors, returning the
+/// sub-bin index. Bins at either edge of the spectrum are returned as-is
+/// since they have no neighbor on one side to interpolate against.
+fn interpolate_peak_bin(spectrum_db: &[f32]) -> f64 {
+    let peak = (1..spectrum_db.len())
+        .max_by(|&a, &b| spectrum_db[a].total_cmp(&spectrum_db[b]))
+        .unwrap_or(0);
 
-    let sample_format = config.sample_format();
-    let config = config.into();
-
-    std::thread::spawn(move || {
-        match sample_format {
-            cpal::SampleFormat::F32 => run_stream::<f32>(&device, &config, shared_data),
-            cpal::SampleFormat::I16 => run_stream::<i16>(&device, &config, shared_data),
-            cpal::SampleFormat::U16 => run_stream::<u16>(&device, &config, shared_data),
-            _ => panic!("Unsupported sample format"),
+    if peak == 0 || peak + 1 >= spectrum_db.len() {
+        return peak as f64;
+    }
+
+    let a = spectrum_db[peak - 1];
+    let b = spectrum_db[peak];
+    let c = spectrum_db[peak + 1];
+    let denom = a - 2.0 * b + c;
+    if denom == 0.0 {
+        return peak as f64;
+    }
+
+    let offset = (0.5 * (a - c) / denom).clamp(-0.5, 0.5);
+    peak as f64 + offset as f64
+}
+
+/// Viridis-style colormap: maps a dB magnitude (clamped to the noise floor) to a color,
+/// dark purple for the quietest bins through blue and green to bright yellow for the loudest.
+fn viridis_color(db: f32) -> egui::Color32 {
+    const STOPS: [(f32, f32, f32); 5] = [
+        (0.267, 0.005, 0.329),
+        (0.283, 0.141, 0.458),
+        (0.254, 0.265, 0.530),
+        (0.207, 0.372, 0.553),
+        (0.127, 0.567, 0.551),
+    ];
+    const LAST: (f32, f32, f32) = (0.993, 0.906, 0.144);
+
+    let t = ((db - NOISE_FLOOR_DB) / -NOISE_FLOOR_DB).clamp(0.0, 1.0);
+    let segment = t * STOPS.len() as f32;
+    let index = (segment as usize).min(STOPS.len() - 1);
+    let frac = segment - index as f32;
+
+    let (r0, g0, b0) = STOPS[index];
+    let (r1, g1, b1) = if index + 1 < STOPS.len() {
+        STOPS[index + 1]
+    } else {
+        LAST
+    };
+
+    let lerp = |a: f32, b: f32| (a + (b - a) * frac) * 255.0;
+    egui::Color32::from_rgb(lerp(r0, r1) as u8, lerp(g0, g1) as u8, lerp(b0, b1) as u8)
+}
+
+/// Builds a single-column image for one spectrum frame, to be written into the
+/// scrolling spectrogram texture with `TextureHandle::set_partial` rather than
+/// rebuilding the whole waterfall image every frame.
+///
+/// `height` is the caller's current bin count, but `spectrum` can still be a
+/// stale, differently-sized frame left over from before an FFT length change
+/// reaches the audio thread (see the `spectrogram_version` rewind handling at
+/// the call site). Skip bins that don't fit rather than trusting the two to
+/// already agree.
+fn spectrogram_column(spectrum: &[f32], height: usize) -> egui::ColorImage {
+    let mut image = egui::ColorImage::new([1, height], egui::Color32::BLACK);
+    for (y, &db) in spectrum.iter().enumerate() {
+        if y >= height {
+            break;
         }
-    });
+        // Bin 0 (DC) is drawn at the bottom of the image.
+        let row = height - 1 - y;
+        image.pixels[row] = viridis_color(db);
+    }
+    image
+}
+
+/// Lists the names of input devices on `host` that expose a usable input config.
+fn input_device_names(host: &cpal::Host) -> Vec<String> {
+    host.input_devices()
+        .map(|devices| {
+            devices
+                .filter(|d| d.default_input_config().is_ok())
+                .filter_map(|d| d.name().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Finds an input device by name, since `cpal` has no direct lookup.
+fn find_input_device(host: &cpal::Host, name: &str) -> Option<cpal::Device> {
+    host.input_devices()
+        .ok()?
+        .find(|d| d.name().map(|n| n == name).unwrap_or(false))
+}
+
+/// Looks up `name` on `host` and (re)builds its capture stream, returning the
+/// error message to show in the UI instead of panicking when the device has
+/// gone away or doesn't support capture.
+fn select_device(
+    host: &cpal::Host,
+    name: &str,
+    shared_data: Arc<Mutex<AudioData>>,
+) -> (Option<cpal::Stream>, Option<String>) {
+    match find_input_device(host, name) {
+        Some(device) => match build_stream(&device, shared_data) {
+            Ok(stream) => (Some(stream), None),
+            Err(err) => (None, Some(err)),
+        },
+        None => (None, Some(format!("Input device \"{name}\" is not available"))),
+    }
+}
+
+/// Builds, configures and starts playing the capture stream for `device`,
+/// dispatching on its native sample format.
+fn build_stream(device: &cpal::Device, shared_data: Arc<Mutex<AudioData>>) -> Result<cpal::Stream, String> {
+    let config = device
+        .default_input_config()
+        .map_err(|e| format!("No supported input config: {e}"))?;
+    let sample_format = config.sample_format();
+    let config: cpal::StreamConfig = config.into();
+
+    shared_data.lock().unwrap().sample_rate = config.sample_rate.0;
+
+    match sample_format {
+        cpal::SampleFormat::F32 => run_stream::<f32>(device, &config, shared_data),
+        cpal::SampleFormat::I16 => run_stream::<i16>(device, &config, shared_data),
+        cpal::SampleFormat::U16 => run_stream::<u16>(device, &config, shared_data),
+        _ => Err("Unsupported sample format".to_string()),
+    }
 }
 
 fn run_stream<T>(
     device: &cpal::Device,
     config: &cpal::StreamConfig,
     shared_data: Arc<Mutex<AudioData>>,
-) where
+) -> Result<cpal::Stream, String>
+where
     T: cpal::Sample + cpal::SizedSample
 {
-    let mut buffer = vec![0.0f32; FFT_SIZE];
-    let mut index = 0;
+    let mut fft_len = DEFAULT_FFT_SIZE;
     let mut planner = FftPlanner::<f32>::new();
-    let fft = planner.plan_fft_forward(FFT_SIZE);
+    let mut fft = planner.plan_fft_forward(fft_len);
+
+    let mut window_kind = WindowKind::Hann;
+    let (mut window_coeffs, mut window_gain) = window_kind.table(fft_len);
+
+    let mut overlap_kind = OverlapKind::Half;
+    let mut hop = overlap_kind.hop_size(fft_len);
+    let mut samples_since_hop = 0usize;
+
+    let mut ring: HeapRb<f32> = HeapRb::new(fft_len);
+    ring.push_iter_overwrite(std::iter::repeat_n(0.0f32, fft_len));
 
     let stream = device.build_input_stream(
         config,
         move |data: &[f32], _| {
             for &sample in data {
-                buffer[index] = sample.to_f32().unwrap();
-                index += 1;
+                ring.push_overwrite(sample.to_f32().unwrap());
+                samples_since_hop += 1;
+
+                if samples_since_hop >= hop {
+                    samples_since_hop = 0;
+                    let mut data = shared_data.lock().unwrap();
+
+                    if data.fft_len != fft_len || data.overlap != overlap_kind {
+                        fft_len = data.fft_len;
+                        overlap_kind = data.overlap;
+                        hop = overlap_kind.hop_size(fft_len);
+                        fft = planner.plan_fft_forward(fft_len);
+                        ring = HeapRb::new(fft_len);
+                        ring.push_iter_overwrite(std::iter::repeat_n(0.0f32, fft_len));
+                        let (coeffs, gain) = window_kind.table(fft_len);
+                        window_coeffs = coeffs;
+                        window_gain = gain;
+                        data.waveform = vec![0.0; fft_len];
+                        data.spectrum = vec![0.0; fft_len / 2];
+                        data.spectrogram.clear();
+                        data.spectrogram_version = 0;
+                        continue;
+                    }
+
+                    if data.window != window_kind {
+                        window_kind = data.window;
+                        let (coeffs, gain) = window_kind.table(fft_len);
+                        window_coeffs = coeffs;
+                        window_gain = gain;
+                    }
 
-                if index >= FFT_SIZE {
-                    let mut input: Vec<Complex32> =
-                        buffer.iter().map(|&x| Complex32::new(x, 0.0)).collect();
+                    let buffer: Vec<f32> = ring.iter().copied().collect();
+
+                    let mut input: Vec<Complex32> = buffer
+                        .iter()
+                        .zip(&window_coeffs)
+                        .map(|(&x, &w)| Complex32::new(x * w, 0.0))
+                        .collect();
                     fft.process(&mut input);
 
-                    let mut spectrum = vec![0.0f32; FFT_SIZE / 2];
-                    for i in 0..FFT_SIZE / 2 {
-                        spectrum[i] = (input[i].norm().log10() + 1.0) * 10.0;
+                    let mut spectrum = vec![0.0f32; fft_len / 2];
+                    for i in 0..fft_len / 2 {
+                        let magnitude = input[i].norm() / window_gain;
+                        let dbfs = 20.0 * (magnitude / (fft_len as f32 / 2.0)).log10();
+                        spectrum[i] = dbfs.max(NOISE_FLOOR_DB);
                     }
 
-                    let mut data = shared_data.lock().unwrap();
+                    let peak_bin = interpolate_peak_bin(&spectrum);
+                    data.peak_frequency = peak_bin * data.sample_rate as f64 / fft_len as f64;
+                    data.peak_magnitude_db = spectrum[peak_bin.round() as usize];
+
                     data.waveform.copy_from_slice(&buffer);
                     data.spectrum.copy_from_slice(&spectrum);
-                    index = 0;
+
+                    if data.spectrogram.len() >= SPECTROGRAM_HISTORY {
+                        data.spectrogram.pop_front();
+                    }
+                    data.spectrogram.push_back(spectrum);
+                    data.spectrogram_version += 1;
                 }
             }
         },
         move |err| eprintln!("Stream error: {:?}", err),
         Some(std::time::Duration::from_secs(4)),
-    ).unwrap();
+    )
+    .map_err(|e| format!("Failed to build input stream: {e}"))?;
 
-    stream.play().unwrap();
-    std::thread::park(); // mantém thread ativa
+    stream
+        .play()
+        .map_err(|e| format!("Failed to start input stream: {e}"))?;
+    Ok(stream)
 }